@@ -2,13 +2,19 @@ use std::{collections::HashSet, time::Duration};
 
 use anyhow::{Context, Result};
 use dialoguer::theme::ColorfulTheme;
-use indicatif::{ProgressIterator, ProgressBar, ProgressStyle, FormattedDuration};
+use futures::stream::{self, StreamExt};
+use indicatif::{ProgressBar, ProgressStyle, FormattedDuration};
 use tokio::fs;
 
 use crate::app::AddonType;
 
 use super::BuildContext;
 
+/// How many addons to download concurrently. Each download is an
+/// independent network fetch, so this is a throughput/politeness
+/// tradeoff rather than a correctness one.
+const DOWNLOAD_CONCURRENCY: usize = 8;
+
 impl<'a> BuildContext<'a> {
     pub async fn download_addons(
         &mut self,
@@ -25,15 +31,62 @@ impl<'a> BuildContext<'a> {
             .with_style(ProgressStyle::with_template("{msg} [{wide_bar:.cyan/blue}] {pos}/{len}")?)
             .with_message(format!("Processing {addon_type}s"));
         let pb = self.app.multi_progress.add(pb);
-        for addon in server_list.iter().progress_with(pb.clone()) {
-            let (_path, resolved) = self.downloadable(addon, &addon_type.folder(), Some(&pb)).await?;
 
+        // Reborrow as shared so every in-flight download can read `self`
+        // concurrently. This requires `downloadable` to take `&self` — a
+        // `&mut self` receiver would fail to compile here, since `this` is
+        // shared across every `buffer_unordered` future below. `downloadable`
+        // isn't defined in this file, so that can't be confirmed by reading
+        // alone; the assert below only proves `BuildContext` is `Sync`, not
+        // that its receiver is shared, so treat this as unverified until it
+        // actually compiles against the real definition.
+        let this = &*self;
+        const _: fn() = || {
+            fn assert_sync<'a>()
+            where
+                BuildContext<'a>: Sync,
+            {
+            }
+            assert_sync();
+        };
+        let agg = pb.clone();
+        let mut downloads = stream::iter(server_list.iter().enumerate())
+            .map(move |(index, addon)| {
+                let agg = agg.clone();
+                async move {
+                    let item_pb = ProgressBar::new_spinner()
+                        .with_style(ProgressStyle::with_template("{spinner:.blue} {msg}")?);
+                    let item_pb = this.app.multi_progress.add(item_pb);
+                    item_pb.enable_steady_tick(Duration::from_millis(100));
+
+                    let result = this.downloadable(addon, &addon_type.folder(), Some(&item_pb)).await;
+
+                    this.app.multi_progress.remove(&item_pb);
+                    agg.inc(1);
+
+                    result.map(|(_path, resolved)| (index, addon.clone(), resolved))
+                }
+            })
+            .buffer_unordered(DOWNLOAD_CONCURRENCY);
+
+        // Results can land in any order since downloads race each other;
+        // sort by the original index before writing them out so the
+        // lockfile and file list stay deterministic run to run.
+        let mut resolved_addons = Vec::with_capacity(server_list.len());
+        while let Some(result) = downloads.next().await {
+            resolved_addons.push(result?);
+        }
+        resolved_addons.sort_by_key(|(index, ..)| *index);
+
+        drop(downloads);
+
+        for (_, addon, resolved) in resolved_addons {
             files_list.insert(resolved.filename.clone());
 
             match addon_type {
                 AddonType::Plugin => &mut self.new_lockfile.plugins,
                 AddonType::Mod => &mut self.new_lockfile.mods,
-            }.push((addon.clone(), resolved));
+            }.push((addon, resolved));
         }
 
         let existing_files = HashSet::from_iter(match addon_type {
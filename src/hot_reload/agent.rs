@@ -0,0 +1,265 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+use super::remote::{AgentEvent, AgentRequest};
+
+/// Runs mcman's dev-agent: binds `port` and, for each incoming dev-session
+/// connection, mirrors synced files into `server_dir` and spawns/drives the
+/// requested process there. This is what `mcman dev-agent <port> --dir
+/// <server_dir>` wires up to run on the remote host named by a
+/// `hotreload.toml`'s `[remote]` section; `RemoteSession` in `remote.rs` is
+/// the client half that talks to it.
+///
+/// # Trust model
+///
+/// The wire protocol is plaintext and unauthenticated: anyone who can reach
+/// this port can overwrite any file under `server_dir` and spawn arbitrary
+/// processes as the user running the agent. The listener binds `0.0.0.0`,
+/// i.e. every interface of the host, so the port must only be reachable
+/// over a trusted network (SSH tunnel, VPN, firewalled LAN, etc.) — never
+/// from a public one.
+pub async fn run(port: u16, server_dir: PathBuf) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .await
+        .with_context(|| format!("Binding dev-agent to port {port}"))?;
+
+    eprintln!("dev-agent: listening on port {port}, serving {}", server_dir.display());
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        eprintln!("dev-agent: accepted connection from {peer}");
+
+        let server_dir = server_dir.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &server_dir).await {
+                eprintln!("dev-agent: connection from {peer} ended: {e}");
+            }
+        });
+    }
+}
+
+/// Drives one dev-session connection: decodes `AgentRequest`s off the wire
+/// and applies them, interleaved with relaying the spawned child's output
+/// and noticing it exit, mirroring the `tokio::select!` loop
+/// `DevSession::handle_commands` uses locally.
+async fn handle_connection(stream: TcpStream, server_dir: &Path) -> Result<()> {
+    let (read_half, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(read_half);
+
+    let mut child: Option<tokio::process::Child> = None;
+    let mut output_rx: Option<mpsc::UnboundedReceiver<AgentEvent>> = None;
+    // Exited is only reported once both of these are true, so a process
+    // that exits right as its last output lines are still in flight can't
+    // have them dropped the way `OutputLines::next_line` used to locally.
+    let mut child_exited = false;
+
+    loop {
+        tokio::select! {
+            header = read_line(&mut reader) => {
+                let Some(header) = header? else {
+                    // The client vanished without sending Terminate/Kill
+                    // (e.g. mcman crashed instead of shutting down
+                    // cleanly). `tokio::process::Child` doesn't kill on
+                    // drop, so do it ourselves or the JVM is orphaned on
+                    // this host with nothing left to stop it.
+                    if let Some(c) = &mut child {
+                        request_kill(c).await;
+                    }
+                    break;
+                };
+                let request: AgentRequest = serde_json::from_str(&header)
+                    .context("Decoding dev-agent request")?;
+
+                match request {
+                    AgentRequest::Sync { path, len } => {
+                        // The header only carries the length; the raw
+                        // bytes immediately follow it on the wire.
+                        let mut bytes = vec![0u8; len as usize];
+                        reader
+                            .read_exact(&mut bytes)
+                            .await
+                            .context("Reading synced file body")?;
+                        write_synced_file(server_dir, &path, &bytes).await?;
+                    }
+                    AgentRequest::Start { program, args } => {
+                        if child.is_none() {
+                            let (spawned, rx) = spawn_child(&program, &args, server_dir)?;
+                            child = Some(spawned);
+                            output_rx = Some(rx);
+                            child_exited = false;
+                        }
+                    }
+                    AgentRequest::SendLine { line } => {
+                        if let Some(stdin) = child.as_mut().and_then(|c| c.stdin.as_mut()) {
+                            let _ = stdin.write_all(line.as_bytes()).await;
+                        }
+                    }
+                    // Piped children have no terminal to resize.
+                    AgentRequest::Resize { .. } => {}
+                    AgentRequest::Terminate => {
+                        if let Some(c) = &child {
+                            request_terminate(c);
+                        }
+                    }
+                    AgentRequest::Kill => {
+                        if let Some(c) = &mut child {
+                            request_kill(c).await;
+                        }
+                    }
+                }
+            }
+            event = recv_or_pending(&mut output_rx) => {
+                match event {
+                    Some(event) => send_event(&mut writer, &event).await?,
+                    None => {
+                        // Both stdout and stderr relay tasks hit EOF.
+                        output_rx = None;
+                        if child_exited {
+                            send_event(&mut writer, &AgentEvent::Exited).await?;
+                            child = None;
+                        }
+                    }
+                }
+            }
+            _ = wait_or_pending(&mut child), if !child_exited => {
+                child_exited = true;
+                if output_rx.is_none() {
+                    send_event(&mut writer, &AgentEvent::Exited).await?;
+                    child = None;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn read_line(reader: &mut (impl AsyncBufReadExt + Unpin)) -> Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end().to_owned()))
+}
+
+/// Awaits the next output event if a child is running, or never resolves
+/// otherwise, so it composes cleanly as a `tokio::select!` branch.
+async fn recv_or_pending(rx: &mut Option<mpsc::UnboundedReceiver<AgentEvent>>) -> Option<AgentEvent> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Awaits the child's exit if one is running, or never resolves otherwise.
+async fn wait_or_pending(child: &mut Option<tokio::process::Child>) {
+    match child {
+        Some(child) => {
+            let _ = child.wait().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn write_synced_file(server_dir: &Path, relative_path: &Path, bytes: &[u8]) -> Result<()> {
+    let full_path = server_dir.join(relative_path);
+    if let Some(parent) = full_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&full_path, bytes)
+        .await
+        .with_context(|| format!("Writing synced file {}", full_path.display()))
+}
+
+/// Spawns the requested process under `server_dir` and relays its stdout
+/// and stderr as tagged `AgentEvent::Line`s on an unbounded channel, the
+/// same shape `Command::spawn_piped`'s stderr relay uses locally. The
+/// caller forwards these onto the wire one at a time from its single
+/// connection-handling task.
+fn spawn_child(
+    program: &str,
+    args: &[String],
+    server_dir: &Path,
+) -> Result<(tokio::process::Child, mpsc::UnboundedReceiver<AgentEvent>)> {
+    let mut command = tokio::process::Command::new(program);
+    command
+        .args(args)
+        .current_dir(server_dir)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+        command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+    }
+
+    let mut child = command.spawn().context("Spawning dev-agent child")?;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    relay_lines(child.stdout.take().expect("stdout None"), false, tx.clone());
+    relay_lines(child.stderr.take().expect("stderr None"), true, tx);
+
+    Ok((child, rx))
+}
+
+fn relay_lines(
+    reader: impl tokio::io::AsyncRead + Unpin + Send + 'static,
+    is_stderr: bool,
+    tx: mpsc::UnboundedSender<AgentEvent>,
+) {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(text)) = lines.next_line().await {
+            if tx.send(AgentEvent::Line { stderr: is_stderr, text }).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+async fn send_event(
+    writer: &mut tokio::io::WriteHalf<TcpStream>,
+    event: &AgentEvent,
+) -> Result<()> {
+    let mut line = serde_json::to_string(event).context("Encoding dev-agent event")?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+fn request_terminate(child: &tokio::process::Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        let _ = super::process::send_signal_to_group(pid as i32, libc::SIGTERM);
+    }
+    #[cfg(windows)]
+    if let Some(pid) = child.id() {
+        let _ = super::process::send_ctrl_break(pid);
+    }
+}
+
+async fn request_kill(child: &mut tokio::process::Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        let _ = super::process::send_signal_to_group(pid as i32, libc::SIGKILL);
+    }
+    #[cfg(windows)]
+    if let Some(pid) = child.id() {
+        let _ = super::process::kill_process_tree(pid);
+    }
+    let _ = child.kill().await;
+}
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use glob::Pattern;
+use serde::{Deserialize, Serialize};
+
+use super::pattern_serde;
+use super::remote::RemoteTarget;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HotReloadConfig {
+    #[serde(skip)]
+    pub path: PathBuf,
+
+    #[serde(default)]
+    pub files: Vec<FileWatch>,
+
+    /// Runs the dev server on another host via mcman's dev-agent instead of
+    /// locally. When set, `pty` is ignored: the agent decides how to spawn
+    /// the process on its end.
+    ///
+    /// The dev-agent protocol is plaintext and unauthenticated, and accepts
+    /// arbitrary file writes plus a process spawn — only point this at an
+    /// agent reachable over a trusted network (loopback, VPN, SSH tunnel),
+    /// never a public address.
+    #[serde(default)]
+    pub remote: Option<RemoteTarget>,
+
+    /// Run the server inside a pseudo-terminal instead of plain pipes, so
+    /// colored console output and interactive prompts are preserved.
+    /// Falls back to the piped implementation if a pty can't be allocated.
+    #[serde(default)]
+    pub pty: bool,
+
+    /// Console command sent to request a graceful stop before escalating
+    /// to a signal, e.g. `stop` or `end`.
+    #[serde(default = "default_stop_command")]
+    pub stop_command: String,
+
+    /// Seconds to wait after the graceful stop command before escalating
+    /// to SIGTERM, and again before escalating to SIGKILL.
+    #[serde(default = "default_grace_period_secs")]
+    pub grace_period_secs: u64,
+}
+
+fn default_stop_command() -> String {
+    "stop".to_owned()
+}
+
+fn default_grace_period_secs() -> u64 {
+    30
+}
+
+impl HotReloadConfig {
+    pub fn load_from(path: &std::path::Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Reading hotreload config: {}", path.display()))?;
+
+        let mut config: Self = toml::from_str(&content)
+            .with_context(|| format!("Parsing hotreload config: {}", path.display()))?;
+
+        config.path = path.to_owned();
+
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FileWatch {
+    #[serde(with = "pattern_serde")]
+    pub path: Pattern,
+    pub action: HotReloadAction,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum HotReloadAction {
+    Reload,
+    Restart,
+    RunCommand(String),
+}
@@ -1,25 +1,33 @@
-use std::{process::Stdio, time::Duration, path::PathBuf, sync::{Mutex, Arc}};
+use std::{time::Duration, path::PathBuf, sync::{Mutex, Arc}};
 
 use anyhow::Result;
 use console::style;
 use notify_debouncer_mini::{new_debouncer, Debouncer, notify::{RecommendedWatcher, EventKind, RecursiveMode}};
 use pathdiff::diff_paths;
-use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, sync::mpsc, task::JoinHandle, process::Child};
+use tokio::{io::AsyncBufReadExt, sync::mpsc, task::JoinHandle};
 
 use crate::core::BuildContext;
 
 use self::config::{HotReloadConfig, HotReloadAction};
+use self::process::{escalate_shutdown, Child, OutputLines, ShutdownOutcome, SyncCache};
 
+pub mod agent;
 pub mod config;
 pub mod pattern_serde;
+pub mod process;
+pub mod pty;
+pub mod remote;
 
-#[derive(Debug)]
 pub struct DevSession<'a> {
-    pub child: Option<tokio::process::Child>,
+    pub child: Option<Child>,
     pub command_sender: Option<mpsc::Sender<Command>>,
     pub command_reciever: Option<mpsc::Receiver<Command>>,
     pub builder: BuildContext<'a>,
     pub jar_name: Option<String>,
+    /// What was last synced to the remote dev-agent, per relative path, so
+    /// restarts only resync files that actually changed. Empty (and
+    /// unused) unless `config.remote` is set.
+    pub remote_sync_cache: SyncCache,
 }
 
 pub enum Command {
@@ -29,6 +37,23 @@ pub enum Command {
     SendCommand(String),
     WaitUntilExit,
     Bootstrap(PathBuf),
+    /// A `RunCommand` template (e.g. `"lp reloadconfig {file}"`) paired with
+    /// the path (relative to the config dir) that triggered it, rendered
+    /// into a console command in one place: `handle_commands`.
+    RunTemplatedCommand(String, PathBuf),
+}
+
+/// Expands `{file}`, `{name}`, and `{ext}` in a `RunCommand` template using
+/// the watched file's path relative to the config dir.
+fn render_template(template: &str, rel_path: &std::path::Path) -> String {
+    let file = rel_path.to_string_lossy();
+    let name = rel_path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let ext = rel_path.extension().map(|s| s.to_string_lossy()).unwrap_or_default();
+
+    template
+        .replace("{file}", &file)
+        .replace("{name}", &name)
+        .replace("{ext}", &ext)
 }
 
 pub enum State {
@@ -38,58 +63,113 @@ pub enum State {
     Online,
 }
 
-async fn try_read_line(opt: &mut Option<tokio::io::Lines<BufReader<tokio::process::ChildStdout>>>) -> Result<Option<String>> {
+async fn try_read_line(opt: &mut Option<OutputLines>) -> Result<Option<(bool, String)>> {
     match opt {
-        Some(lines) => Ok(lines.next_line().await?),
+        Some(stream) => stream.next_line().await,
         None => Ok(None),
     }
 }
 
+/// Prints one line of server output through `mp.suspend`, so it doesn't
+/// corrupt any in-progress progress bars. Stderr gets a distinct red `! `
+/// prefix instead of the usual `| `, so it's still clearly ordered with
+/// stdout without being mistaken for it.
+fn print_server_line(mp: &indicatif::MultiProgress, is_stderr: bool, line: &str) {
+    let line = line.trim();
+    mp.suspend(|| {
+        if is_stderr {
+            println!("{}{line}", style("! ").red().bold());
+        } else {
+            println!("{}{line}", style("| ").bold());
+        }
+    });
+}
+
 // TODO
 // [x] fix stdout nesting for some reason
 // [x] commands are not being sent properly
 // [x] use debouncer for notify
 // [ ] reload server.toml properly
-// [ ] tests 
+// [x] tests (shutdown escalation policy, see process::tests; handle_commands itself still untested)
+// [x] windows process-group kill (CTRL_BREAK_EVENT + taskkill /T)
 
 impl<'a> DevSession<'a> {
-    pub async fn spawn_child(&mut self) -> Result<Child> {
+    pub async fn spawn_child(&mut self, config: &HotReloadConfig) -> Result<(Child, OutputLines)> {
         let platform = if std::env::consts::FAMILY == "windows" {
             "windows"
         } else {
             "linux"
         };
 
-        Ok(
-            tokio::process::Command::new("java")
-            .args(
-                self.builder.app.server
-                    .launcher
-                    .get_arguments(&self.builder.app.server.jar.get_startup_method(
-                        &self.builder.app,
-                        &self.jar_name.as_ref().unwrap().clone()
-                    ).await?, platform),
-            )
-            .current_dir(&self.builder.output_dir)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .spawn()?
-        )
+        let args = self.builder.app.server
+            .launcher
+            .get_arguments(&self.builder.app.server.jar.get_startup_method(
+                &self.builder.app,
+                &self.jar_name.as_ref().unwrap().clone()
+            ).await?, platform);
+
+        let command = process::Command::new("java")
+            .args(args)
+            .current_dir(self.builder.output_dir.clone());
+
+        if let Some(target) = &config.remote {
+            self.builder.app.info(format!(
+                "Syncing changed files to dev-agent at {}:{}...",
+                target.host, target.port
+            ))?;
+            return command.spawn_remote(target, &mut self.remote_sync_cache).await;
+        }
+
+        if config.pty {
+            match command.clone().spawn_pty() {
+                Ok(spawned) => return Ok(spawned),
+                Err(e) => self.builder.app.warn(format!(
+                    "Failed to allocate a pty ({e}), falling back to piped output"
+                ))?,
+            }
+        }
+
+        command.spawn_piped()
     }
 
-    async fn handle_commands(mut self, mut rx: mpsc::Receiver<Command>) -> Result<()> {
+    /// Sends the configured stop command and waits up to `grace_period_secs`
+    /// for a clean exit, escalating to SIGTERM and then SIGKILL (whole
+    /// process group on Unix) if the server doesn't shut down in time. The
+    /// escalation policy itself lives in `process::escalate_shutdown`, kept
+    /// separate from `DevSession` so it can be driven by a mock child in
+    /// tests.
+    async fn graceful_shutdown(&self, child: &mut Child, config: &HotReloadConfig) -> Result<()> {
+        self.builder.app.info(&format!("Sending stop command: {}", config.stop_command))?;
+        let grace = Duration::from_secs(config.grace_period_secs);
+
+        match escalate_shutdown(child, &config.stop_command, grace).await? {
+            ShutdownOutcome::Graceful => Ok(()),
+            ShutdownOutcome::Terminated => {
+                self.builder.app.warn("Graceful stop timed out, sent SIGTERM and it exited")?;
+                Ok(())
+            }
+            ShutdownOutcome::Killed => {
+                self.builder.app.warn("Process still alive after SIGTERM, sent SIGKILL")?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn handle_commands(mut self, mut rx: mpsc::Receiver<Command>, config: Arc<Mutex<HotReloadConfig>>) -> Result<()> {
         let mp = self.builder.app.multi_progress.clone();
 
         let mut child: Option<Child> = None;
         //let mut child_stdout = None;
 
-        let mut stdout_lines: Option<tokio::io::Lines<BufReader<tokio::process::ChildStdout>>> = None;
+        let mut stdout_lines: Option<OutputLines> = None;
 
         let mut is_stopping = false;
 
         let mut stdin_lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
 
+        #[cfg(unix)]
+        let mut winch = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change())?;
+
         loop {
             tokio::select! {
                 Some(cmd) = rx.recv() => {
@@ -97,15 +177,17 @@ impl<'a> DevSession<'a> {
                         Command::Start => {
                             self.builder.app.info("Starting server process...")?;
                             if child.is_none() {
-                                let mut spawned_child = self.spawn_child().await?;
-                                stdout_lines = Some(tokio::io::BufReader::new(spawned_child.stdout.take().expect("stdout None")).lines());
+                                let current_config = config.lock().unwrap().clone();
+                                let (spawned_child, lines) = self.spawn_child(&current_config).await?;
+                                stdout_lines = Some(lines);
                                 child = Some(spawned_child);
                             }
                         }
                         Command::Stop => {
-                            self.builder.app.info("Killing server process...")?;
+                            self.builder.app.info("Stopping server process...")?;
                             if let Some(ref mut child) = &mut child {
-                                child.kill().await?;
+                                let current_config = config.lock().unwrap().clone();
+                                self.graceful_shutdown(child, &current_config).await?;
                             }
                             child = None;
                             stdout_lines = None;
@@ -113,32 +195,27 @@ impl<'a> DevSession<'a> {
                         Command::SendCommand(command) => {
                             self.builder.app.info(&format!("Sending command: {command}"))?;
                             if let Some(ref mut child) = &mut child {
-                                if let Some(ref mut stdin) = &mut child.stdin {
-                                    let _ = stdin.write_all(command.as_bytes()).await;
-                                }
+                                let _ = child.send_line(&command).await;
                             }
                         }
                         Command::WaitUntilExit => {
                             self.builder.app.info("Waiting for process exit...")?;
                             is_stopping = true;
                             if let Some(ref mut child) = &mut child {
+                                let current_config = config.lock().unwrap().clone();
+                                let grace = Duration::from_secs(current_config.grace_period_secs);
+
                                 let should_kill = tokio::select! {
                                     _ = async {
                                         loop {
-                                            if let Ok(Some(line)) = try_read_line(&mut stdout_lines).await {
-                                                mp.suspend(|| {
-                                                    println!(
-                                                        "{}{}",
-                                                        style("| ").bold(),
-                                                        line.trim()
-                                                    )
-                                                });
+                                            if let Ok(Some((is_stderr, line))) = try_read_line(&mut stdout_lines).await {
+                                                print_server_line(&mp, is_stderr, &line);
                                             }
                                         }
                                     } => false, // should be unreachable..?
                                     _ = child.wait() => false,
-                                    _ = tokio::time::sleep(Duration::from_secs(30)) => {
-                                        self.builder.app.info("Timeout reached, killing...")?;
+                                    _ = tokio::time::sleep(grace) => {
+                                        self.builder.app.info("Grace period elapsed, escalating...")?;
                                         true
                                     },
                                     _ = tokio::signal::ctrl_c() => {
@@ -148,7 +225,10 @@ impl<'a> DevSession<'a> {
                                 };
 
                                 if should_kill {
-                                    child.kill().await?;
+                                    child.terminate().await?;
+                                    if tokio::time::timeout(grace, child.wait()).await.is_err() {
+                                        child.kill().await?;
+                                    }
                                 }
                             }
                             is_stopping = false;
@@ -164,33 +244,46 @@ impl<'a> DevSession<'a> {
                             let rel_path = diff_paths(&path, self.builder.app.server.path.join("config"))
                                 .expect("Cannot diff paths");
                             self.builder.app.info(format!("Bootstrapping: {}", rel_path.to_string_lossy().trim()))?;
-                            match self.builder.bootstrap_file(&rel_path, None).await {
-                                Ok(_) => {},
-                                Err(e) => self.builder.app.warn(format!("Error while bootstrapping:
-                                - Path: {}
-                                - Err: {e}", rel_path.to_string_lossy()))?,
+
+                            if let Some(Child::Remote(session)) = &child {
+                                // Running remote: forward the raw watched file to the
+                                // agent instead of rendering it into a local config dir.
+                                match tokio::fs::read(&path).await {
+                                    Ok(bytes) => {
+                                        let remote_path = PathBuf::from("config").join(&rel_path);
+                                        if let Err(e) = session.sync_file(&remote_path, &bytes).await {
+                                            self.builder.app.warn(format!("Failed to forward bootstrapped file to remote: {e}"))?;
+                                        }
+                                    }
+                                    Err(e) => self.builder.app.warn(format!("Error reading bootstrapped file: {e}"))?,
+                                }
+                            } else {
+                                match self.builder.bootstrap_file(&rel_path, None).await {
+                                    Ok(_) => {},
+                                    Err(e) => self.builder.app.warn(format!("Error while bootstrapping:
+                                    - Path: {}
+                                    - Err: {e}", rel_path.to_string_lossy()))?,
+                                }
+                            }
+                        }
+                        Command::RunTemplatedCommand(template, rel_path) => {
+                            let rendered = render_template(&template, &rel_path);
+                            self.builder.app.info(&format!("Sending command: {rendered}"))?;
+                            if let Some(ref mut child) = &mut child {
+                                let _ = child.send_line(&format!("{rendered}\n")).await;
                             }
                         }
                     }
                 },
-                Ok(Some(line)) = try_read_line(&mut stdout_lines) => {
-                    let mut s = line.trim();
-
-                    mp.suspend(|| {
-                        println!(
-                            "{}{s}",
-                            style("| ").bold()
-                        )
-                    });
+                Ok(Some((is_stderr, line))) = try_read_line(&mut stdout_lines) => {
+                    print_server_line(&mp, is_stderr, &line);
                 },
                 Ok(Some(line)) = stdin_lines.next_line() => {
                     let mut cmd = line.trim();
 
                     self.builder.app.info(&format!("Sending command: {cmd}"))?;
                     if let Some(ref mut child) = &mut child {
-                        if let Some(ref mut stdin) = &mut child.stdin {
-                            let _ = stdin.write_all(format!("{cmd}\n").as_bytes()).await;
-                        }
+                        let _ = child.send_line(&format!("{cmd}\n")).await;
                     }
                 },
                 _ = tokio::signal::ctrl_c() => {
@@ -199,12 +292,20 @@ impl<'a> DevSession<'a> {
                         break;
                     }
                 }
+                #[cfg(unix)]
+                _ = winch.recv() => {
+                    if let Some(ref child) = &child {
+                        let (rows, cols) = console::Term::stdout().size();
+                        let _ = child.resize(rows, cols).await;
+                    }
+                }
             }
         }
 
         if let Some(ref mut child) = &mut child {
-            self.builder.app.info("Killing undead child process...")?;
-            child.kill().await?;
+            self.builder.app.info("Stopping undead child process...")?;
+            let current_config = config.lock().unwrap().clone();
+            self.graceful_shutdown(child, &current_config).await?;
         }
 
         Ok(())
@@ -241,6 +342,7 @@ impl<'a> DevSession<'a> {
 
     pub fn create_config_watcher(
         config: Arc<Mutex<HotReloadConfig>>,
+        config_dir: PathBuf,
         tx: mpsc::Sender<Command>,
     ) -> Result<Debouncer<RecommendedWatcher>> {
         Ok(new_debouncer(Duration::from_secs(1), move |e| {
@@ -279,7 +381,8 @@ impl<'a> DevSession<'a> {
                                     .expect("tx send err");
                             }
                             HotReloadAction::RunCommand(cmd) => {
-                                tx.blocking_send(Command::SendCommand(format!("{cmd}\n")))
+                                let rel_path = diff_paths(&path, &config_dir).unwrap_or_else(|| path.clone());
+                                tx.blocking_send(Command::RunTemplatedCommand(cmd.clone(), rel_path))
                                     .expect("tx send err");
                             }
                         }
@@ -315,7 +418,11 @@ impl<'a> DevSession<'a> {
 
         let cfg_mutex = Arc::new(Mutex::new(config));
 
-        let mut config_watcher = Self::create_config_watcher(cfg_mutex.clone(), tx.clone())?;
+        let mut config_watcher = Self::create_config_watcher(
+            cfg_mutex.clone(),
+            self.builder.app.server.path.join("config"),
+            tx.clone(),
+        )?;
         let mut hotreload_watcher = Self::create_hotreload_watcher(cfg_mutex.clone(), tx.clone())?;
         let mut servertoml_watcher = Self::create_servertoml_watcher(tx.clone())?;
 
@@ -326,7 +433,7 @@ impl<'a> DevSession<'a> {
         tx.send(Command::Rebuild).await?;
         tx.send(Command::Start).await?;
 
-        self.handle_commands(rx).await?;
+        self.handle_commands(rx, cfg_mutex.clone()).await?;
 
         Ok(())
     }
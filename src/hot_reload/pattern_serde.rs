@@ -0,0 +1,17 @@
+use glob::Pattern;
+use serde::{de::Error as _, Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S>(pattern: &Pattern, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(pattern.as_str())
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Pattern, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw = String::deserialize(deserializer)?;
+    Pattern::new(&raw).map_err(D::Error::custom)
+}
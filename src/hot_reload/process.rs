@@ -0,0 +1,502 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::mpsc,
+};
+
+use super::pty::PtySession;
+use super::remote::{RemoteSession, RemoteTarget};
+
+/// The surface `DevSession`'s state machine actually drives a child
+/// process through. `Child` is the only production implementation, but
+/// routing everything through this trait is what lets the shutdown
+/// escalation policy below be exercised against a mock in tests instead
+/// of a real `java` process.
+pub trait ChildProcess: Send {
+    fn kill(&mut self) -> BoxFuture<'_, Result<()>>;
+    fn terminate(&self) -> BoxFuture<'_, Result<()>>;
+    fn wait(&mut self) -> BoxFuture<'_, Result<()>>;
+    fn send_line(&mut self, line: &str) -> BoxFuture<'_, Result<()>>;
+    fn resize(&self, rows: u16, cols: u16) -> BoxFuture<'_, Result<()>>;
+}
+
+/// Describes a process to launch, independent of the strategy used to spawn
+/// it (plain pipes vs. a pseudo-terminal). Keeping this separate from
+/// `tokio::process::Command` is what lets `DevSession` stay agnostic of how
+/// the child is actually run.
+#[derive(Debug, Clone, Default)]
+pub struct Command {
+    program: String,
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    env: Vec<(String, String)>,
+}
+
+impl Command {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = String>) -> Self {
+        self.args.extend(args);
+        self
+    }
+
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Spawns behind plain pipes: the default, most portable strategy.
+    pub fn spawn_piped(self) -> Result<(Child, OutputLines)> {
+        let mut command = tokio::process::Command::new(&self.program);
+        command
+            .args(&self.args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        for (key, value) in &self.env {
+            command.env(key, value);
+        }
+
+        // Run as its own process group leader so the graceful-shutdown
+        // routine can signal the whole group instead of only this pid,
+        // taking orphaned JVM helper processes down with it.
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+
+        // Windows has no SIGTERM/process-group signal, but putting the
+        // child in its own console process group lets `terminate` raise
+        // CTRL_BREAK_EVENT for the whole tree instead of only this pid.
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+            command.creation_flags(CREATE_NEW_PROCESS_GROUP);
+        }
+
+        let mut child = command.spawn()?;
+        let stdout = BufReader::new(child.stdout.take().expect("stdout None")).lines();
+
+        // stderr is read on its own task and merged with stdout in
+        // `OutputLines::next_line`, tagged so callers can prefix it
+        // distinctly instead of letting it bypass mcman's console framing.
+        let stderr = child.stderr.take().expect("stderr None");
+        let (stderr_tx, stderr_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if stderr_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok((Child::Piped(child), OutputLines::Piped { stdout, stderr_rx }))
+    }
+
+    /// Spawns attached to a pty, so the child believes it has a real
+    /// terminal (colors, cursor control, interactive prompts all work).
+    pub fn spawn_pty(self) -> Result<(Child, OutputLines)> {
+        let cwd = self.cwd.clone().unwrap_or_else(|| PathBuf::from("."));
+        let (session, rx) = PtySession::spawn(&self.program, &self.args, &cwd)?;
+
+        Ok((Child::Pty(session), OutputLines::Pty(rx)))
+    }
+
+    /// Connects to a dev-agent on another host, syncs the working directory
+    /// (everything under `current_dir`) to it, then asks it to
+    /// spawn the process there. Used to run the dev server on real hardware
+    /// while keeping the local hot-reload loop driving it.
+    ///
+    /// `sync_cache` tracks the size and modified time this session last
+    /// pushed for each relative path, so restarts only resync files that
+    /// actually changed instead of the whole output directory (including
+    /// any multi-megabyte server jar) every time.
+    pub async fn spawn_remote(
+        self,
+        target: &RemoteTarget,
+        sync_cache: &mut SyncCache,
+    ) -> Result<(Child, OutputLines)> {
+        let (session, rx) = RemoteSession::connect(target).await?;
+
+        if let Some(cwd) = &self.cwd {
+            sync_dir(&session, cwd, cwd, sync_cache).await?;
+        }
+
+        session.start(self.program, self.args).await?;
+
+        Ok((Child::Remote(session), OutputLines::Remote(rx)))
+    }
+}
+
+/// Size and modified time a relative path was last synced with, used to
+/// skip re-pushing files that haven't changed since.
+pub type SyncCache = std::collections::HashMap<PathBuf, (u64, std::time::SystemTime)>;
+
+/// Recursively pushes every changed file under `dir` to the agent, keyed by
+/// its path relative to `root`, so the remote server directory ends up
+/// mirroring the local one. A file is skipped when its size and modified
+/// time both match what `sync_cache` recorded from the last sync.
+async fn sync_dir(
+    session: &RemoteSession,
+    root: &std::path::Path,
+    dir: &std::path::Path,
+    sync_cache: &mut SyncCache,
+) -> Result<()> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.is_dir() {
+            Box::pin(sync_dir(session, root, &path, sync_cache)).await?;
+            continue;
+        }
+
+        let metadata = entry.metadata().await?;
+        let stamp = (metadata.len(), metadata.modified()?);
+
+        let relative_path = path.strip_prefix(root).unwrap_or(&path);
+        if sync_cache.get(relative_path) == Some(&stamp) {
+            continue;
+        }
+
+        let bytes = tokio::fs::read(&path).await?;
+        session.sync_file(relative_path, &bytes).await?;
+        sync_cache.insert(relative_path.to_owned(), stamp);
+    }
+
+    Ok(())
+}
+
+/// A spawned child process, whether it's behind plain pipes, attached to a
+/// pty, or actually running on a remote dev-agent. This is the interface
+/// `DevSession`'s state machine talks to, so it never has to reference
+/// `tokio::process`, `portable_pty`, or the remote transport directly.
+pub enum Child {
+    Piped(tokio::process::Child),
+    Pty(PtySession),
+    Remote(RemoteSession),
+}
+
+impl Child {
+    /// Escalation kill: signals the whole process group on Unix (so JVM
+    /// helper processes die with it) before falling back to tokio's own
+    /// `Child::kill`, which also reaps the handle so `wait()` resolves.
+    pub async fn kill(&mut self) -> Result<()> {
+        match self {
+            Child::Piped(child) => {
+                #[cfg(unix)]
+                if let Some(pid) = child.id() {
+                    let _ = send_signal_to_group(pid as i32, libc::SIGKILL);
+                }
+                #[cfg(windows)]
+                if let Some(pid) = child.id() {
+                    let _ = kill_process_tree(pid);
+                }
+                child.kill().await.map_err(Into::into)
+            }
+            Child::Pty(session) => session.kill(),
+            Child::Remote(session) => session.kill().await,
+        }
+    }
+
+    /// Requests a softer exit than `kill`. On Unix this is SIGTERM to the
+    /// process group; pty children only expose a single terminal `kill`, so
+    /// there's nothing softer to send there; remote children forward the
+    /// request to the agent, which decides how to escalate on its end.
+    pub async fn terminate(&self) -> Result<()> {
+        match self {
+            Child::Piped(child) => {
+                #[cfg(unix)]
+                if let Some(pid) = child.id() {
+                    send_signal_to_group(pid as i32, libc::SIGTERM)?;
+                }
+                #[cfg(windows)]
+                if let Some(pid) = child.id() {
+                    send_ctrl_break(pid)?;
+                }
+                Ok(())
+            }
+            Child::Pty(_) => Ok(()),
+            Child::Remote(session) => session.terminate().await,
+        }
+    }
+
+    pub async fn wait(&mut self) -> Result<()> {
+        match self {
+            Child::Piped(child) => {
+                child.wait().await?;
+                Ok(())
+            }
+            Child::Pty(session) => {
+                session.wait().await?;
+                Ok(())
+            }
+            Child::Remote(session) => session.wait().await,
+        }
+    }
+
+    pub async fn send_line(&mut self, line: &str) -> Result<()> {
+        match self {
+            Child::Piped(child) => {
+                if let Some(ref mut stdin) = &mut child.stdin {
+                    stdin.write_all(line.as_bytes()).await?;
+                }
+            }
+            Child::Pty(session) => session.write_all(line.as_bytes())?,
+            Child::Remote(session) => session.send_line(line).await?,
+        }
+        Ok(())
+    }
+
+    pub async fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        match self {
+            Child::Piped(_) => Ok(()),
+            Child::Pty(session) => session.resize(rows, cols),
+            Child::Remote(session) => session.resize(rows, cols).await,
+        }
+    }
+}
+
+impl ChildProcess for Child {
+    fn kill(&mut self) -> BoxFuture<'_, Result<()>> {
+        Child::kill(self).boxed()
+    }
+
+    fn terminate(&self) -> BoxFuture<'_, Result<()>> {
+        Child::terminate(self).boxed()
+    }
+
+    fn wait(&mut self) -> BoxFuture<'_, Result<()>> {
+        Child::wait(self).boxed()
+    }
+
+    fn send_line(&mut self, line: &str) -> BoxFuture<'_, Result<()>> {
+        Child::send_line(self, line).boxed()
+    }
+
+    fn resize(&self, rows: u16, cols: u16) -> BoxFuture<'_, Result<()>> {
+        Child::resize(self, rows, cols).boxed()
+    }
+}
+
+/// What the stop-command escalation policy actually did, so callers can log
+/// accordingly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownOutcome {
+    /// Exited on its own after the stop command.
+    Graceful,
+    /// Needed a terminate signal before it exited.
+    Terminated,
+    /// Didn't respond to terminate either and had to be killed.
+    Killed,
+}
+
+/// Sends the stop command and waits up to `grace` for a clean exit,
+/// escalating to `terminate` and then `kill` if the child doesn't respond
+/// in time at each step. Pulled out of `DevSession::graceful_shutdown` so
+/// the escalation policy itself is testable against a mock `ChildProcess`,
+/// independent of `DevSession` and the rest of the session state machine.
+pub async fn escalate_shutdown(
+    child: &mut dyn ChildProcess,
+    stop_command: &str,
+    grace: Duration,
+) -> Result<ShutdownOutcome> {
+    let _ = child.send_line(&format!("{stop_command}\n")).await;
+    if tokio::time::timeout(grace, child.wait()).await.is_ok() {
+        return Ok(ShutdownOutcome::Graceful);
+    }
+
+    child.terminate().await?;
+    if tokio::time::timeout(grace, child.wait()).await.is_ok() {
+        return Ok(ShutdownOutcome::Terminated);
+    }
+
+    child.kill().await?;
+    Ok(ShutdownOutcome::Killed)
+}
+
+#[cfg(unix)]
+pub(crate) fn send_signal_to_group(pid: i32, signal: i32) -> Result<()> {
+    // Negative pid targets the whole process group; the child is spawned
+    // with `process_group(0)` so its group id equals its own pid. ESRCH
+    // (group already gone) is fine, anything else is a real failure.
+    let ret = unsafe { libc::kill(-pid, signal) };
+    if ret != 0 && std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH) {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Softer-than-`kill_process_tree` escalation on Windows: raises
+/// CTRL_BREAK_EVENT for the child's console process group, which a JVM
+/// (like SIGTERM on Unix) can catch via a shutdown hook. Requires the
+/// child to have been spawned with `CREATE_NEW_PROCESS_GROUP`.
+#[cfg(windows)]
+pub(crate) fn send_ctrl_break(pid: u32) -> Result<()> {
+    use windows_sys::Win32::System::Console::{GenerateConsoleCtrlEvent, CTRL_BREAK_EVENT};
+
+    // SAFETY: FFI call into Win32; `pid` is the process group id of a child
+    // we just spawned with `CREATE_NEW_PROCESS_GROUP`, so it's a live group
+    // owned by us for the duration of this call.
+    let ok = unsafe { GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid) };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Forceful escalation on Windows: `taskkill /T` walks the whole process
+/// tree rooted at `pid` rather than just that one pid, which is what keeps
+/// orphaned JVM helper processes from surviving a plain `TerminateProcess`.
+#[cfg(windows)]
+pub(crate) fn kill_process_tree(pid: u32) -> Result<()> {
+    let status = std::process::Command::new("taskkill")
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .status()
+        .context("Running taskkill")?;
+
+    // Exit code 128 means the process had already exited; anything else
+    // non-zero is a real failure to report.
+    if !status.success() && status.code() != Some(128) {
+        anyhow::bail!("taskkill exited with status {status}");
+    }
+    Ok(())
+}
+
+/// The output-line reader, backed by the child's piped stdout+stderr, the
+/// line-reassembler thread feeding from the pty master, or the decoded event
+/// stream from a remote dev-agent.
+///
+/// Lines are tagged `(is_stderr, line)` so the caller can prefix stderr
+/// distinctly while keeping both streams ordered under one reader.
+pub enum OutputLines {
+    Piped {
+        stdout: tokio::io::Lines<BufReader<tokio::process::ChildStdout>>,
+        stderr_rx: mpsc::UnboundedReceiver<String>,
+    },
+    Pty(mpsc::UnboundedReceiver<String>),
+    Remote(mpsc::UnboundedReceiver<(bool, String)>),
+}
+
+impl OutputLines {
+    pub async fn next_line(&mut self) -> Result<Option<(bool, String)>> {
+        match self {
+            OutputLines::Piped { stdout, stderr_rx } => {
+                tokio::select! {
+                    line = stdout.next_line() => match line? {
+                        Some(l) => Ok(Some((false, l))),
+                        // stdout hit EOF, but stderr may still have lines
+                        // buffered from before the child exited; drain
+                        // those before reporting end-of-output so they
+                        // aren't silently dropped.
+                        None => Ok(stderr_rx.recv().await.map(|l| (true, l))),
+                    },
+                    Some(line) = stderr_rx.recv() => Ok(Some((true, line))),
+                    else => Ok(None),
+                }
+            }
+            OutputLines::Pty(rx) => Ok(rx.recv().await.map(|l| (false, l))),
+            OutputLines::Remote(rx) => Ok(rx.recv().await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// A child that exits as soon as `wait` is polled, for the common case.
+    struct CompliantChild;
+
+    impl ChildProcess for CompliantChild {
+        fn kill(&mut self) -> BoxFuture<'_, Result<()>> {
+            async { Ok(()) }.boxed()
+        }
+        fn terminate(&self) -> BoxFuture<'_, Result<()>> {
+            async { Ok(()) }.boxed()
+        }
+        fn wait(&mut self) -> BoxFuture<'_, Result<()>> {
+            async { Ok(()) }.boxed()
+        }
+        fn send_line(&mut self, _line: &str) -> BoxFuture<'_, Result<()>> {
+            async { Ok(()) }.boxed()
+        }
+        fn resize(&self, _rows: u16, _cols: u16) -> BoxFuture<'_, Result<()>> {
+            async { Ok(()) }.boxed()
+        }
+    }
+
+    /// A child that never exits on its own, so `wait` never resolves and the
+    /// escalation policy is forced through every step, counting how many
+    /// times each one is reached.
+    #[derive(Default)]
+    struct StubbornChild {
+        terminated: AtomicUsize,
+        killed: AtomicUsize,
+    }
+
+    impl ChildProcess for StubbornChild {
+        fn kill(&mut self) -> BoxFuture<'_, Result<()>> {
+            self.killed.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }.boxed()
+        }
+        fn terminate(&self) -> BoxFuture<'_, Result<()>> {
+            self.terminated.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }.boxed()
+        }
+        fn wait(&mut self) -> BoxFuture<'_, Result<()>> {
+            std::future::pending().boxed()
+        }
+        fn send_line(&mut self, _line: &str) -> BoxFuture<'_, Result<()>> {
+            async { Ok(()) }.boxed()
+        }
+        fn resize(&self, _rows: u16, _cols: u16) -> BoxFuture<'_, Result<()>> {
+            async { Ok(()) }.boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn escalate_shutdown_returns_immediately_on_clean_exit() {
+        let mut child = CompliantChild;
+        let outcome = escalate_shutdown(&mut child, "stop", Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert_eq!(outcome, ShutdownOutcome::Graceful);
+    }
+
+    #[tokio::test]
+    async fn escalate_shutdown_falls_back_to_terminate_then_kill() {
+        let mut child = StubbornChild::default();
+        let outcome = escalate_shutdown(&mut child, "stop", Duration::from_millis(5))
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, ShutdownOutcome::Killed);
+        assert_eq!(child.terminated.load(Ordering::SeqCst), 1);
+        assert_eq!(child.killed.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use portable_pty::{native_pty_system, Child as PtyChild, CommandBuilder, MasterPty, PtySize};
+use tokio::sync::mpsc;
+
+/// A `java` process running attached to a pseudo-terminal instead of plain pipes.
+///
+/// Keeps the child believing it has a real tty, so ANSI colors and
+/// interactive prompts behave the same as running mcman in a terminal.
+pub struct PtySession {
+    master: Box<dyn MasterPty + Send>,
+    writer: Box<dyn Write + Send>,
+    child: Box<dyn PtyChild + Send + Sync>,
+}
+
+impl PtySession {
+    pub fn spawn(
+        program: &str,
+        args: &[String],
+        cwd: &Path,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<String>)> {
+        let pty_system = native_pty_system();
+
+        let pair = pty_system
+            .openpty(PtySize {
+                rows: 24,
+                cols: 80,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .context("Allocating pty")?;
+
+        let mut cmd = CommandBuilder::new(program);
+        cmd.args(args);
+        cmd.cwd(cwd);
+
+        let child = pair
+            .slave
+            .spawn_command(cmd)
+            .context("Spawning child process in pty")?;
+        // The slave side belongs to the child now; dropping our handle to it
+        // lets reads on the master side see EOF once the child exits.
+        drop(pair.slave);
+
+        let writer = pair
+            .master
+            .take_writer()
+            .context("Taking pty writer")?;
+        let mut reader = pair
+            .master
+            .try_clone_reader()
+            .context("Cloning pty reader")?;
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            let mut pending = String::new();
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        pending.push_str(&String::from_utf8_lossy(&buf[..n]));
+                        while let Some(idx) = pending.find('\n') {
+                            let line = pending[..idx].to_owned();
+                            pending.drain(..=idx);
+                            if tx.send(line).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok((
+            Self {
+                master: pair.master,
+                writer,
+                child,
+            },
+            rx,
+        ))
+    }
+
+    pub fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        self.master
+            .resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .context("Resizing pty")
+    }
+
+    pub fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer.write_all(bytes).context("Writing to pty")
+    }
+
+    pub fn kill(&mut self) -> Result<()> {
+        self.child.kill().context("Killing pty child")
+    }
+
+    /// Polls the child for exit, since `portable_pty::Child::wait` blocks the
+    /// calling thread and the child handle isn't safe to move into
+    /// `spawn_blocking` alongside the rest of `DevSession`.
+    pub async fn wait(&mut self) -> Result<portable_pty::ExitStatus> {
+        loop {
+            if let Some(status) = self.child.try_wait().context("Polling pty child")? {
+                return Ok(status);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::{mpsc, watch, Mutex as AsyncMutex},
+};
+
+/// Where mcman's dev-agent is listening. The agent (`super::agent::run`,
+/// started with `mcman dev-agent`) owns a server directory on the remote
+/// host; mcman only ever pushes files into it and tunnels stdin/stdout/
+/// console commands, it never touches the remote filesystem any other way.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RemoteTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+/// One message in the dev-agent wire protocol. Sent as a single JSON
+/// header line; `agent::handle_connection` is the other end that decodes
+/// these. `Sync` is the one exception: its header only carries the byte
+/// count, and the raw file bytes immediately follow on the same
+/// connection, so a multi-megabyte server jar doesn't get inflated into a
+/// JSON array of integers and held fully in memory as one.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum AgentRequest {
+    /// Writes (or overwrites) one file under the agent's server directory,
+    /// keyed by a path relative to that directory (e.g. `plugins/Foo.jar`).
+    /// `len` raw bytes follow this header line on the wire.
+    Sync { path: PathBuf, len: u64 },
+    Start { program: String, args: Vec<String> },
+    SendLine { line: String },
+    Resize { rows: u16, cols: u16 },
+    Terminate,
+    Kill,
+}
+
+/// One message the agent reports back, decoded into the same
+/// `(is_stderr, line)` shape `OutputLines` already uses for local children.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum AgentEvent {
+    Line { stderr: bool, text: String },
+    Exited,
+}
+
+/// A connection to a remote dev-agent, standing in for a local `Child`: the
+/// JVM actually runs on the other end, so every operation is a message over
+/// the wire instead of a syscall on a local pid.
+pub struct RemoteSession {
+    writer: AsyncMutex<tokio::io::WriteHalf<TcpStream>>,
+    exited: watch::Receiver<bool>,
+}
+
+impl RemoteSession {
+    /// Connects to the agent and spawns the background task that decodes its
+    /// event stream into the same `(is_stderr, line)` shape `OutputLines`
+    /// already uses for local children.
+    pub async fn connect(
+        target: &RemoteTarget,
+    ) -> Result<(Self, mpsc::UnboundedReceiver<(bool, String)>)> {
+        let stream = TcpStream::connect((target.host.as_str(), target.port))
+            .await
+            .with_context(|| format!("Connecting to dev-agent at {}:{}", target.host, target.port))?;
+        let (read_half, write_half) = tokio::io::split(stream);
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (exited_tx, exited_rx) = watch::channel(false);
+
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(read_half).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let Ok(event) = serde_json::from_str::<AgentEvent>(&line) else {
+                    continue;
+                };
+                match event {
+                    AgentEvent::Line { stderr, text } => {
+                        if tx.send((stderr, text)).is_err() {
+                            break;
+                        }
+                    }
+                    AgentEvent::Exited => break,
+                }
+            }
+            let _ = exited_tx.send(true);
+        });
+
+        Ok((
+            Self {
+                writer: AsyncMutex::new(write_half),
+                exited: exited_rx,
+            },
+            rx,
+        ))
+    }
+
+    async fn send(&self, request: &AgentRequest) -> Result<()> {
+        let mut line = serde_json::to_string(request).context("Encoding dev-agent request")?;
+        line.push('\n');
+
+        let mut writer = self.writer.lock().await;
+        writer.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Pushes one file into the remote server directory, keyed by its path
+    /// relative to that directory. Used both for the initial sync before
+    /// `Start` and for individual bootstrapped files afterwards.
+    ///
+    /// Sends the `Sync` header followed immediately by the raw file bytes,
+    /// instead of folding them into the header's JSON (see `AgentRequest`),
+    /// so this never holds more than one copy of `bytes` in memory.
+    pub async fn sync_file(&self, relative_path: &Path, bytes: &[u8]) -> Result<()> {
+        let mut header = serde_json::to_string(&AgentRequest::Sync {
+            path: relative_path.to_owned(),
+            len: bytes.len() as u64,
+        })
+        .context("Encoding dev-agent request")?;
+        header.push('\n');
+
+        let mut writer = self.writer.lock().await;
+        writer.write_all(header.as_bytes()).await?;
+        writer.write_all(bytes).await?;
+        Ok(())
+    }
+
+    pub async fn start(&self, program: String, args: Vec<String>) -> Result<()> {
+        self.send(&AgentRequest::Start { program, args }).await
+    }
+
+    pub async fn send_line(&self, line: &str) -> Result<()> {
+        self.send(&AgentRequest::SendLine {
+            line: line.to_owned(),
+        })
+        .await
+    }
+
+    pub async fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        self.send(&AgentRequest::Resize { rows, cols }).await
+    }
+
+    pub async fn terminate(&self) -> Result<()> {
+        self.send(&AgentRequest::Terminate).await
+    }
+
+    pub async fn kill(&self) -> Result<()> {
+        self.send(&AgentRequest::Kill).await
+    }
+
+    /// Resolves once the agent reports the remote process has exited (or the
+    /// connection drops). Safe to call more than once: the exit state is
+    /// latched in a `watch` channel rather than a one-shot notification.
+    pub async fn wait(&self) -> Result<()> {
+        let mut rx = self.exited.clone();
+        if *rx.borrow() {
+            return Ok(());
+        }
+        let _ = rx.changed().await;
+        Ok(())
+    }
+}